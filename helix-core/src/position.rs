@@ -1,9 +1,8 @@
 use std::borrow::Cow;
 
 use crate::{
-    chars::char_is_line_ending,
     graphemes::{ensure_grapheme_boundary_prev, grapheme_width, RopeGraphemes},
-    line_ending::line_end_char_index,
+    line_ending::{line_end_char_index, str_is_line_ending},
     RopeSlice,
 };
 
@@ -23,21 +22,33 @@ impl Position {
         self.row == 0 && self.col == 0
     }
 
-    // TODO: generalize
-    pub fn traverse(self, text: &crate::Tendril) -> Self {
+    /// Computes the new `Position` after traversing (moving through) `text`,
+    /// starting from this position. Walks grapheme clusters rather than
+    /// chars, so it correctly advances past multi-char line endings like
+    /// `\r\n` as a single row increment.
+    pub fn traverse(self, text: RopeSlice) -> Self {
         let Self { mut row, mut col } = self;
-        // TODO: there should be a better way here
-        let mut chars = text.chars().peekable();
-        while let Some(ch) = chars.next() {
-            if char_is_line_ending(ch) && !(ch == '\r' && chars.peek() == Some(&'\n')) {
+
+        for grapheme in RopeGraphemes::new(text) {
+            let grapheme: Cow<str> = grapheme.into();
+            if str_is_line_ending(&grapheme) {
                 row += 1;
                 col = 0;
             } else {
-                col += 1;
+                col += grapheme.chars().count();
             }
         }
+
         Self { row, col }
     }
+
+    /// Like `traverse()`, but also returns the number of chars in `text`, so
+    /// transaction/position-mapping code can advance a `Position` across an
+    /// inserted slice and get the resulting char index in one pass, without
+    /// re-scanning from the start of the document.
+    pub fn traverse_with_char_idx(self, text: RopeSlice) -> (Self, usize) {
+        (self.traverse(text), text.len_chars())
+    }
 }
 
 impl From<(usize, usize)> for Position {
@@ -54,24 +65,39 @@ impl From<Position> for tree_sitter::Point {
         Self::new(pos.row, pos.col)
     }
 }
-/// Convert a character index to (line, column) coordinates.
+/// Returns the visual width of a single grapheme, expanding `\t` to the
+/// next tab stop given the visual column it starts at instead of a fixed
+/// width. A `tab_width` of `0` is treated the same as `1`, so a bad config
+/// value can't divide by zero and crash on an otherwise valid buffer.
+fn grapheme_width_at_col(g: &str, col: usize, tab_width: usize) -> usize {
+    if g == "\t" {
+        let tab_width = tab_width.max(1);
+        tab_width - (col % tab_width)
+    } else {
+        grapheme_width(g)
+    }
+}
+
+/// Convert a character index to (line, column) coordinates, where column is
+/// the *visual* column, i.e. the sum of the display widths of the graphemes
+/// preceding `pos` on that line. Use this for cursor geometry.
+///
+/// See also `char_coords_at_pos()` for the "objective" column used by e.g.
+/// the row:column display in the status line.
 ///
-/// TODO: this should be split into two methods: one for visual
-/// row/column, and one for "objective" row/column (possibly with
-/// the column specified in `char`s).  The former would be used
-/// for cursor movement, and the latter would be used for e.g. the
-/// row:column display in the status line.
-pub fn coords_at_pos(text: RopeSlice, pos: usize) -> Position {
+/// `tab_width` is the number of columns a tab advances to the next
+/// multiple of; pass `1` if tabs should simply count as a single column.
+pub fn coords_at_pos(text: RopeSlice, pos: usize, tab_width: usize) -> Position {
     let line = text.char_to_line(pos);
 
     let line_start = text.line_to_char(line);
     let pos = ensure_grapheme_boundary_prev(text, pos);
-    let col = RopeGraphemes::new(text.slice(line_start..pos))
-        .map(|g| {
-            let g: Cow<str> = g.into();
-            grapheme_width(&g)
-        })
-        .sum();
+
+    let mut col = 0;
+    for g in RopeGraphemes::new(text.slice(line_start..pos)) {
+        let g: Cow<str> = g.into();
+        col += grapheme_width_at_col(&g, col, tab_width);
+    }
 
     Position::new(line, col)
 }
@@ -83,7 +109,17 @@ pub fn coords_at_pos(text: RopeSlice, pos: usize) -> Position {
 /// `false` corresponds to properly round-tripping with `coords_at_pos()`,
 /// whereas `true` will ensure that block cursors don't jump off the
 /// end of the line.
-pub fn pos_at_coords(text: RopeSlice, coords: Position, is_1_width: bool) -> usize {
+///
+/// `tab_width` must match the value passed to `coords_at_pos()` for this
+/// to round-trip correctly. A `col` that lands inside a tab's expansion
+/// snaps to the char offset of the tab itself, same as it would for any
+/// other multi-column grapheme.
+pub fn pos_at_coords(
+    text: RopeSlice,
+    coords: Position,
+    is_1_width: bool,
+    tab_width: usize,
+) -> usize {
     let Position { row, col } = coords;
     let line_start = text.line_to_char(row);
     let line_end = if is_1_width {
@@ -96,7 +132,7 @@ pub fn pos_at_coords(text: RopeSlice, coords: Position, is_1_width: bool) -> usi
     let mut col_char_offset = 0;
     for g in RopeGraphemes::new(text.slice(line_start..line_end)) {
         let g: Cow<str> = g.into();
-        let next_col = prev_col + grapheme_width(&g);
+        let next_col = prev_col + grapheme_width_at_col(&g, prev_col, tab_width);
 
         if next_col > col {
             break;
@@ -109,6 +145,199 @@ pub fn pos_at_coords(text: RopeSlice, coords: Position, is_1_width: bool) -> usi
     line_start + col_char_offset
 }
 
+/// Convert a character index to (line, column) coordinates, where column is
+/// the *objective* column, i.e. the number of chars since the start of the
+/// line rather than a display width. Unlike `coords_at_pos()`, this isn't
+/// affected by grapheme or tab width, so it's stable for e.g. the row:column
+/// display in the status line even amid wide CJK glyphs.
+pub fn char_coords_at_pos(text: RopeSlice, pos: usize) -> Position {
+    let line = text.char_to_line(pos);
+
+    let line_start = text.line_to_char(line);
+    let pos = ensure_grapheme_boundary_prev(text, pos);
+    let col = text.slice(line_start..pos).len_chars();
+
+    Position::new(line, col)
+}
+
+/// Convert (line, column) coordinates, where column is a count of chars
+/// since the start of the line, to a character index. Inverse of
+/// `char_coords_at_pos()`.
+///
+/// A `col` that lands inside a multi-char grapheme cluster is snapped back
+/// to the start of that cluster, same as `pos_at_coords()`.
+pub fn pos_at_char_coords(text: RopeSlice, coords: Position) -> usize {
+    let Position { row, col } = coords;
+    let line_start = text.line_to_char(row);
+    let line_end = text.line_to_char((row + 1).min(text.len_lines()));
+
+    let pos = line_start + col.min(line_end - line_start);
+    ensure_grapheme_boundary_prev(text, pos)
+}
+
+/// Decides whether `g`, starting at visual column `col`, wraps to a new row
+/// given `viewport_width`, and returns `(wrapped, col, width)`: `col` is the
+/// column `g` actually starts at (reset to `0` if it wrapped) and `width` is
+/// `g`'s display width recomputed at that column. Recomputing after the wrap
+/// decision matters for tabs, whose width depends on the column they start
+/// at: a tab that straddles a wrap boundary must expand from column `0`, not
+/// from its pre-wrap column.
+fn wrap_grapheme(
+    col: usize,
+    g: &str,
+    viewport_width: usize,
+    tab_width: usize,
+) -> (bool, usize, usize) {
+    let width = grapheme_width_at_col(g, col, tab_width);
+
+    if viewport_width > 0 && col + width > viewport_width {
+        (true, 0, grapheme_width_at_col(g, 0, tab_width))
+    } else {
+        (false, col, width)
+    }
+}
+
+/// Walks `graphemes`, wrapping to a new row whenever the next grapheme would
+/// overflow `viewport_width`, and returns the number of wraps performed
+/// together with the final visual column. A `viewport_width` of `0` means no
+/// wrapping happens.
+fn wrapped_grapheme_walk<'a>(
+    graphemes: impl Iterator<Item = Cow<'a, str>>,
+    viewport_width: usize,
+    tab_width: usize,
+) -> (usize, usize) {
+    let mut row = 0;
+    let mut col = 0;
+
+    for g in graphemes {
+        let (wrapped, new_col, width) = wrap_grapheme(col, &g, viewport_width, tab_width);
+        if wrapped {
+            row += 1;
+        }
+        col = new_col + width;
+    }
+
+    (row, col)
+}
+
+fn rope_graphemes(slice: RopeSlice) -> impl Iterator<Item = Cow<str>> {
+    RopeGraphemes::new(slice).map(|g| g.into())
+}
+
+/// Convert a character index to (row, column) coordinates, where wrapping a
+/// line at `viewport_width` columns starts a new visual row. This is the
+/// core primitive for soft-wrap: each logical line may consume more than one
+/// visual row, and `row` here counts visual rows rather than logical lines.
+///
+/// A `viewport_width` of `0` disables wrapping, matching `coords_at_pos()`.
+///
+/// Note: this rescans every preceding line on each call, so it's O(total
+/// lines) rather than O(line length). Don't call it once per visible row in
+/// a render loop over a large file without caching line-wrap counts first.
+pub fn visual_coords_at_pos(
+    text: RopeSlice,
+    pos: usize,
+    viewport_width: usize,
+    tab_width: usize,
+) -> Position {
+    let line = text.char_to_line(pos);
+    let pos = ensure_grapheme_boundary_prev(text, pos);
+
+    let mut row = 0;
+    for prev_line in 0..line {
+        let start = text.line_to_char(prev_line);
+        let end = text.line_to_char(prev_line + 1);
+        let (wraps, _) = wrapped_grapheme_walk(
+            rope_graphemes(text.slice(start..end)),
+            viewport_width,
+            tab_width,
+        );
+        row += wraps + 1;
+    }
+
+    let line_start = text.line_to_char(line);
+    let (wraps, col) = wrapped_grapheme_walk(
+        rope_graphemes(text.slice(line_start..pos)),
+        viewport_width,
+        tab_width,
+    );
+    row += wraps;
+
+    Position::new(row, col)
+}
+
+/// Convert (row, column) coordinates, where `row` counts visual rows after
+/// wrapping at `viewport_width` columns, to a character index. Inverse of
+/// `visual_coords_at_pos()`.
+///
+/// `is_1_width` specifies whether the position should be treated
+/// as a block cursor or not.  This effects how line-ends are handled.
+/// `false` corresponds to properly round-tripping with `visual_coords_at_pos()`,
+/// whereas `true` will ensure that block cursors don't jump off the
+/// end of the line.
+pub fn pos_at_visual_coords(
+    text: RopeSlice,
+    coords: Position,
+    viewport_width: usize,
+    tab_width: usize,
+    is_1_width: bool,
+) -> usize {
+    let Position {
+        row: target_row,
+        col: target_col,
+    } = coords;
+
+    let mut row = 0;
+    let mut line = 0;
+    while line + 1 < text.len_lines() {
+        let start = text.line_to_char(line);
+        let end = text.line_to_char(line + 1);
+        let (wraps, _) = wrapped_grapheme_walk(
+            rope_graphemes(text.slice(start..end)),
+            viewport_width,
+            tab_width,
+        );
+        let line_rows = wraps + 1;
+
+        if row + line_rows > target_row {
+            break;
+        }
+
+        row += line_rows;
+        line += 1;
+    }
+    let target_wrap_row = target_row - row;
+
+    let line_start = text.line_to_char(line);
+    let line_end = if is_1_width {
+        line_end_char_index(&text, line)
+    } else {
+        text.line_to_char((line + 1).min(text.len_lines()))
+    };
+
+    let mut wrap_row = 0;
+    let mut col = 0;
+    let mut col_char_offset = 0;
+    for g in RopeGraphemes::new(text.slice(line_start..line_end)) {
+        let g: Cow<str> = g.into();
+        let (wrapped, new_col, width) = wrap_grapheme(col, &g, viewport_width, tab_width);
+        if wrapped {
+            wrap_row += 1;
+        }
+        col = new_col;
+
+        if wrap_row > target_wrap_row || (wrap_row == target_wrap_row && col + width > target_col)
+        {
+            break;
+        }
+
+        col += width;
+        col_char_offset += g.chars().count();
+    }
+
+    line_start + col_char_offset
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -120,92 +349,274 @@ mod test {
         assert!(Position::new(0, 5) < Position::new(1, 0));
     }
 
+    #[test]
+    fn test_traverse() {
+        let text = Rope::from("abcd\r\nefg\nhij");
+        let slice = text.slice(..);
+        assert_eq!(Position::new(0, 0).traverse(slice), Position::new(2, 3));
+
+        // Traversing an empty slice doesn't move.
+        assert_eq!(Position::new(3, 2).traverse(text.slice(0..0)), Position::new(3, 2));
+
+        // Traversing a slice that doesn't end in a line ending continues
+        // the current row rather than starting a new one.
+        let partial = Rope::from("abc");
+        assert_eq!(
+            Position::new(1, 1).traverse(partial.slice(..)),
+            Position::new(1, 4)
+        );
+
+        // `col` counts chars, not graphemes: a multi-codepoint grapheme
+        // cluster (base char + combining mark) advances col by 2, not 1.
+        let combining = Rope::from("a̐bc");
+        assert_eq!(
+            Position::new(0, 0).traverse(combining.slice(..)),
+            Position::new(0, 4)
+        );
+    }
+
+    #[test]
+    fn test_traverse_with_char_idx() {
+        let text = Rope::from("abcd\r\nefg\nhij");
+        let slice = text.slice(..);
+        let (pos, char_idx) = Position::new(0, 0).traverse_with_char_idx(slice);
+        assert_eq!(pos, Position::new(2, 3));
+        assert_eq!(char_idx, text.len_chars());
+    }
+
     #[test]
     fn test_coords_at_pos() {
         let text = Rope::from("ḧëḷḷö\nẅöṛḷḋ");
         let slice = text.slice(..);
-        assert_eq!(coords_at_pos(slice, 0), (0, 0).into());
-        assert_eq!(coords_at_pos(slice, 5), (0, 5).into()); // position on \n
-        assert_eq!(coords_at_pos(slice, 6), (1, 0).into()); // position on w
-        assert_eq!(coords_at_pos(slice, 7), (1, 1).into()); // position on o
-        assert_eq!(coords_at_pos(slice, 10), (1, 4).into()); // position on d
+        assert_eq!(coords_at_pos(slice, 0, 1), (0, 0).into());
+        assert_eq!(coords_at_pos(slice, 5, 1), (0, 5).into()); // position on \n
+        assert_eq!(coords_at_pos(slice, 6, 1), (1, 0).into()); // position on w
+        assert_eq!(coords_at_pos(slice, 7, 1), (1, 1).into()); // position on o
+        assert_eq!(coords_at_pos(slice, 10, 1), (1, 4).into()); // position on d
 
         // Test with wide characters.
         let text = Rope::from("今日はいい\n");
         let slice = text.slice(..);
-        assert_eq!(coords_at_pos(slice, 0), (0, 0).into());
-        assert_eq!(coords_at_pos(slice, 1), (0, 2).into());
-        assert_eq!(coords_at_pos(slice, 2), (0, 4).into());
-        assert_eq!(coords_at_pos(slice, 3), (0, 6).into());
-        assert_eq!(coords_at_pos(slice, 4), (0, 8).into());
-        assert_eq!(coords_at_pos(slice, 5), (0, 10).into());
-        assert_eq!(coords_at_pos(slice, 6), (1, 0).into());
+        assert_eq!(coords_at_pos(slice, 0, 1), (0, 0).into());
+        assert_eq!(coords_at_pos(slice, 1, 1), (0, 2).into());
+        assert_eq!(coords_at_pos(slice, 2, 1), (0, 4).into());
+        assert_eq!(coords_at_pos(slice, 3, 1), (0, 6).into());
+        assert_eq!(coords_at_pos(slice, 4, 1), (0, 8).into());
+        assert_eq!(coords_at_pos(slice, 5, 1), (0, 10).into());
+        assert_eq!(coords_at_pos(slice, 6, 1), (1, 0).into());
 
         // test with grapheme clusters
         let text = Rope::from("a̐éö̲\r\n");
         let slice = text.slice(..);
-        assert_eq!(coords_at_pos(slice, 0), (0, 0).into());
-        assert_eq!(coords_at_pos(slice, 2), (0, 1).into());
-        assert_eq!(coords_at_pos(slice, 4), (0, 2).into());
-        assert_eq!(coords_at_pos(slice, 7), (0, 3).into());
-        assert_eq!(coords_at_pos(slice, 9), (1, 0).into());
+        assert_eq!(coords_at_pos(slice, 0, 1), (0, 0).into());
+        assert_eq!(coords_at_pos(slice, 2, 1), (0, 1).into());
+        assert_eq!(coords_at_pos(slice, 4, 1), (0, 2).into());
+        assert_eq!(coords_at_pos(slice, 7, 1), (0, 3).into());
+        assert_eq!(coords_at_pos(slice, 9, 1), (1, 0).into());
 
         let text = Rope::from("किमपि\n");
         let slice = text.slice(..);
-        assert_eq!(coords_at_pos(slice, 0), (0, 0).into());
-        assert_eq!(coords_at_pos(slice, 2), (0, 2).into());
-        assert_eq!(coords_at_pos(slice, 3), (0, 3).into());
-        assert_eq!(coords_at_pos(slice, 5), (0, 5).into());
-        assert_eq!(coords_at_pos(slice, 6), (1, 0).into());
+        assert_eq!(coords_at_pos(slice, 0, 1), (0, 0).into());
+        assert_eq!(coords_at_pos(slice, 2, 1), (0, 2).into());
+        assert_eq!(coords_at_pos(slice, 3, 1), (0, 3).into());
+        assert_eq!(coords_at_pos(slice, 5, 1), (0, 5).into());
+        assert_eq!(coords_at_pos(slice, 6, 1), (1, 0).into());
+
+        // Test with tabs, using a tab width of 4.
+        let text = Rope::from("a\tb\tc");
+        let slice = text.slice(..);
+        assert_eq!(coords_at_pos(slice, 0, 4), (0, 0).into());
+        assert_eq!(coords_at_pos(slice, 1, 4), (0, 1).into()); // position on \t
+        assert_eq!(coords_at_pos(slice, 2, 4), (0, 4).into()); // position on b
+        assert_eq!(coords_at_pos(slice, 3, 4), (0, 5).into()); // position on \t
+        assert_eq!(coords_at_pos(slice, 4, 4), (0, 8).into()); // position on c
+        assert_eq!(coords_at_pos(slice, 5, 4), (0, 9).into());
+
+        // A tab_width of 0 doesn't panic; it's treated the same as 1.
+        assert_eq!(coords_at_pos(slice, 5, 0), coords_at_pos(slice, 5, 1));
     }
 
     #[test]
     fn test_pos_at_coords() {
         let text = Rope::from("ḧëḷḷö\nẅöṛḷḋ");
         let slice = text.slice(..);
-        assert_eq!(pos_at_coords(slice, (0, 0).into(), false), 0);
-        assert_eq!(pos_at_coords(slice, (0, 5).into(), false), 5); // position on \n
-        assert_eq!(pos_at_coords(slice, (0, 6).into(), false), 6); // position after \n
-        assert_eq!(pos_at_coords(slice, (0, 6).into(), true), 5); // position after \n
-        assert_eq!(pos_at_coords(slice, (1, 0).into(), false), 6); // position on w
-        assert_eq!(pos_at_coords(slice, (1, 1).into(), false), 7); // position on o
-        assert_eq!(pos_at_coords(slice, (1, 4).into(), false), 10); // position on d
+        assert_eq!(pos_at_coords(slice, (0, 0).into(), false, 1), 0);
+        assert_eq!(pos_at_coords(slice, (0, 5).into(), false, 1), 5); // position on \n
+        assert_eq!(pos_at_coords(slice, (0, 6).into(), false, 1), 6); // position after \n
+        assert_eq!(pos_at_coords(slice, (0, 6).into(), true, 1), 5); // position after \n
+        assert_eq!(pos_at_coords(slice, (1, 0).into(), false, 1), 6); // position on w
+        assert_eq!(pos_at_coords(slice, (1, 1).into(), false, 1), 7); // position on o
+        assert_eq!(pos_at_coords(slice, (1, 4).into(), false, 1), 10); // position on d
 
         // Test with wide characters.
         let text = Rope::from("今日はいい\n");
         let slice = text.slice(..);
-        assert_eq!(pos_at_coords(slice, (0, 0).into(), false), 0);
-        assert_eq!(pos_at_coords(slice, (0, 1).into(), false), 0);
-        assert_eq!(pos_at_coords(slice, (0, 2).into(), false), 1);
-        assert_eq!(pos_at_coords(slice, (0, 3).into(), false), 1);
-        assert_eq!(pos_at_coords(slice, (0, 4).into(), false), 2);
-        assert_eq!(pos_at_coords(slice, (0, 6).into(), false), 3);
-        assert_eq!(pos_at_coords(slice, (0, 8).into(), false), 4);
-        assert_eq!(pos_at_coords(slice, (0, 10).into(), false), 5);
-        assert_eq!(pos_at_coords(slice, (0, 11).into(), false), 6);
-        assert_eq!(pos_at_coords(slice, (0, 11).into(), true), 5);
-        assert_eq!(pos_at_coords(slice, (1, 0).into(), false), 6);
+        assert_eq!(pos_at_coords(slice, (0, 0).into(), false, 1), 0);
+        assert_eq!(pos_at_coords(slice, (0, 1).into(), false, 1), 0);
+        assert_eq!(pos_at_coords(slice, (0, 2).into(), false, 1), 1);
+        assert_eq!(pos_at_coords(slice, (0, 3).into(), false, 1), 1);
+        assert_eq!(pos_at_coords(slice, (0, 4).into(), false, 1), 2);
+        assert_eq!(pos_at_coords(slice, (0, 6).into(), false, 1), 3);
+        assert_eq!(pos_at_coords(slice, (0, 8).into(), false, 1), 4);
+        assert_eq!(pos_at_coords(slice, (0, 10).into(), false, 1), 5);
+        assert_eq!(pos_at_coords(slice, (0, 11).into(), false, 1), 6);
+        assert_eq!(pos_at_coords(slice, (0, 11).into(), true, 1), 5);
+        assert_eq!(pos_at_coords(slice, (1, 0).into(), false, 1), 6);
 
         // test with grapheme clusters
         let text = Rope::from("a̐éö̲\r\n");
         let slice = text.slice(..);
-        assert_eq!(pos_at_coords(slice, (0, 0).into(), false), 0);
-        assert_eq!(pos_at_coords(slice, (0, 1).into(), false), 2);
-        assert_eq!(pos_at_coords(slice, (0, 2).into(), false), 4);
-        assert_eq!(pos_at_coords(slice, (0, 3).into(), false), 7); // \r\n is one char here
-        assert_eq!(pos_at_coords(slice, (0, 4).into(), false), 9);
-        assert_eq!(pos_at_coords(slice, (0, 4).into(), true), 7);
-        assert_eq!(pos_at_coords(slice, (1, 0).into(), false), 9);
+        assert_eq!(pos_at_coords(slice, (0, 0).into(), false, 1), 0);
+        assert_eq!(pos_at_coords(slice, (0, 1).into(), false, 1), 2);
+        assert_eq!(pos_at_coords(slice, (0, 2).into(), false, 1), 4);
+        assert_eq!(pos_at_coords(slice, (0, 3).into(), false, 1), 7); // \r\n is one char here
+        assert_eq!(pos_at_coords(slice, (0, 4).into(), false, 1), 9);
+        assert_eq!(pos_at_coords(slice, (0, 4).into(), true, 1), 7);
+        assert_eq!(pos_at_coords(slice, (1, 0).into(), false, 1), 9);
 
         let text = Rope::from("किमपि");
         // 2 - 1 - 2 codepoints
         // TODO: delete handling as per https://news.ycombinator.com/item?id=20058454
         let slice = text.slice(..);
-        assert_eq!(pos_at_coords(slice, (0, 0).into(), false), 0);
-        assert_eq!(pos_at_coords(slice, (0, 1).into(), false), 0);
-        assert_eq!(pos_at_coords(slice, (0, 2).into(), false), 2);
-        assert_eq!(pos_at_coords(slice, (0, 3).into(), false), 3);
-        assert_eq!(pos_at_coords(slice, (0, 4).into(), false), 3);
-        assert_eq!(pos_at_coords(slice, (0, 5).into(), false), 5); // eol
+        assert_eq!(pos_at_coords(slice, (0, 0).into(), false, 1), 0);
+        assert_eq!(pos_at_coords(slice, (0, 1).into(), false, 1), 0);
+        assert_eq!(pos_at_coords(slice, (0, 2).into(), false, 1), 2);
+        assert_eq!(pos_at_coords(slice, (0, 3).into(), false, 1), 3);
+        assert_eq!(pos_at_coords(slice, (0, 4).into(), false, 1), 3);
+        assert_eq!(pos_at_coords(slice, (0, 5).into(), false, 1), 5); // eol
+
+        // Test with tabs, using a tab width of 4.
+        let text = Rope::from("a\tb\tc");
+        let slice = text.slice(..);
+        assert_eq!(pos_at_coords(slice, (0, 0).into(), false, 4), 0);
+        assert_eq!(pos_at_coords(slice, (0, 1).into(), false, 4), 1); // position on \t
+        assert_eq!(pos_at_coords(slice, (0, 2).into(), false, 4), 1); // inside \t, snaps back
+        assert_eq!(pos_at_coords(slice, (0, 3).into(), false, 4), 1); // inside \t, snaps back
+        assert_eq!(pos_at_coords(slice, (0, 4).into(), false, 4), 2); // position on b
+        assert_eq!(pos_at_coords(slice, (0, 5).into(), false, 4), 3); // position on \t
+        assert_eq!(pos_at_coords(slice, (0, 6).into(), false, 4), 3); // inside \t, snaps back
+        assert_eq!(pos_at_coords(slice, (0, 8).into(), false, 4), 4); // position on c
+        assert_eq!(pos_at_coords(slice, (0, 9).into(), false, 4), 5);
+    }
+
+    #[test]
+    fn test_char_coords_at_pos() {
+        // Test with wide characters, where visual and char column diverge.
+        let text = Rope::from("今日はいい\n");
+        let slice = text.slice(..);
+        assert_eq!(char_coords_at_pos(slice, 0), (0, 0).into());
+        assert_eq!(char_coords_at_pos(slice, 1), (0, 1).into());
+        assert_eq!(char_coords_at_pos(slice, 2), (0, 2).into());
+        assert_eq!(char_coords_at_pos(slice, 5), (0, 5).into());
+        assert_eq!(char_coords_at_pos(slice, 6), (1, 0).into());
+
+        // Tabs are one char regardless of tab width.
+        let text = Rope::from("a\tb\tc");
+        let slice = text.slice(..);
+        assert_eq!(char_coords_at_pos(slice, 0), (0, 0).into());
+        assert_eq!(char_coords_at_pos(slice, 1), (0, 1).into());
+        assert_eq!(char_coords_at_pos(slice, 2), (0, 2).into());
+        assert_eq!(char_coords_at_pos(slice, 5), (0, 5).into());
+    }
+
+    #[test]
+    fn test_pos_at_char_coords() {
+        let text = Rope::from("今日はいい\n");
+        let slice = text.slice(..);
+        assert_eq!(pos_at_char_coords(slice, (0, 0).into()), 0);
+        assert_eq!(pos_at_char_coords(slice, (0, 2).into()), 2);
+        assert_eq!(pos_at_char_coords(slice, (0, 5).into()), 5);
+        assert_eq!(pos_at_char_coords(slice, (1, 0).into()), 6);
+
+        // Round-trips with char_coords_at_pos().
+        for pos in 0..text.len_chars() {
+            assert_eq!(pos_at_char_coords(slice, char_coords_at_pos(slice, pos)), pos);
+        }
+
+        // A col that lands between a base char and its combining mark
+        // snaps back to the start of the grapheme cluster.
+        let text = Rope::from("a̐bc");
+        let slice = text.slice(..);
+        assert_eq!(pos_at_char_coords(slice, (0, 0).into()), 0);
+        assert_eq!(pos_at_char_coords(slice, (0, 1).into()), 0); // inside a̐, snaps back
+        assert_eq!(pos_at_char_coords(slice, (0, 2).into()), 2);
+    }
+
+    #[test]
+    fn test_visual_coords_at_pos() {
+        // Soft-wrapped at a viewport width of 3 columns:
+        //   row0: "abc"   row1: "def"   row2: "gh\n"   row3: "ij"
+        let text = Rope::from("abcdefgh\nij");
+        let slice = text.slice(..);
+        assert_eq!(visual_coords_at_pos(slice, 0, 3, 1), (0, 0).into());
+        assert_eq!(visual_coords_at_pos(slice, 4, 3, 1), (1, 1).into()); // position on e
+        assert_eq!(visual_coords_at_pos(slice, 7, 3, 1), (2, 1).into()); // position on h
+        assert_eq!(visual_coords_at_pos(slice, 9, 3, 1), (3, 0).into()); // position on i
+        assert_eq!(visual_coords_at_pos(slice, 10, 3, 1), (3, 1).into()); // position on j
+
+        // A viewport width of 0 disables wrapping and matches coords_at_pos().
+        for pos in 0..text.len_chars() {
+            assert_eq!(
+                visual_coords_at_pos(slice, pos, 0, 1),
+                coords_at_pos(slice, pos, 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_visual_coords_at_pos_tab_at_wrap_boundary() {
+        // "ab" leaves one column free, but the tab right after it would
+        // expand by 2 columns (to the stop at 4) and overflow, so it wraps
+        // and must expand starting at column 0 of the next row, not at its
+        // pre-wrap column.
+        let text = Rope::from("ab\tx");
+        let slice = text.slice(..);
+        assert_eq!(visual_coords_at_pos(slice, 0, 3, 4), (0, 0).into());
+        assert_eq!(visual_coords_at_pos(slice, 2, 3, 4), (0, 2).into()); // position on \t
+        assert_eq!(visual_coords_at_pos(slice, 3, 3, 4), (1, 4).into()); // position on x
+        assert_eq!(visual_coords_at_pos(slice, 4, 3, 4), (2, 1).into());
+    }
+
+    #[test]
+    fn test_pos_at_visual_coords() {
+        let text = Rope::from("abcdefgh\nij");
+        let slice = text.slice(..);
+        assert_eq!(pos_at_visual_coords(slice, (0, 0).into(), 3, 1, false), 0);
+        assert_eq!(pos_at_visual_coords(slice, (1, 1).into(), 3, 1, false), 4);
+        assert_eq!(pos_at_visual_coords(slice, (2, 1).into(), 3, 1, false), 7);
+        assert_eq!(pos_at_visual_coords(slice, (3, 0).into(), 3, 1, false), 9);
+        assert_eq!(pos_at_visual_coords(slice, (3, 1).into(), 3, 1, false), 10);
+
+        // Round-trips with visual_coords_at_pos().
+        for pos in 0..text.len_chars() {
+            let coords = visual_coords_at_pos(slice, pos, 3, 1);
+            assert_eq!(pos_at_visual_coords(slice, coords, 3, 1, false), pos);
+        }
+
+        // `is_1_width` on a wrapped row: `false` can cross this line's \n
+        // and land on the first char of the next logical line, while
+        // `true` (block cursor) stops on the \n itself.
+        assert_eq!(pos_at_visual_coords(slice, (2, 3).into(), 3, 1, false), 9);
+        assert_eq!(pos_at_visual_coords(slice, (2, 3).into(), 3, 1, true), 8);
+
+        // The last line has no trailing line ending to clamp before, so
+        // `is_1_width` makes no difference there.
+        assert_eq!(pos_at_visual_coords(slice, (3, 1).into(), 3, 1, true), 10);
+        assert_eq!(pos_at_visual_coords(slice, (3, 2).into(), 3, 1, true), 11);
+    }
+
+    #[test]
+    fn test_pos_at_visual_coords_tab_at_wrap_boundary() {
+        let text = Rope::from("ab\tx");
+        let slice = text.slice(..);
+        assert_eq!(pos_at_visual_coords(slice, (0, 2).into(), 3, 4, false), 2);
+        assert_eq!(pos_at_visual_coords(slice, (1, 4).into(), 3, 4, false), 3);
+        assert_eq!(pos_at_visual_coords(slice, (2, 1).into(), 3, 4, false), 4);
+
+        // Round-trips with visual_coords_at_pos().
+        for pos in 0..text.len_chars() {
+            let coords = visual_coords_at_pos(slice, pos, 3, 4);
+            assert_eq!(pos_at_visual_coords(slice, coords, 3, 4, false), pos);
+        }
     }
 }